@@ -0,0 +1,122 @@
+use probe_rs::debug::DebugInfo;
+use probe_rs::Core;
+
+use capstone::Capstone;
+
+use anyhow::Result;
+
+/// What the read-eval-print loop in [`crate::debug`] should do after handling a line.
+pub enum CliState {
+    Continue,
+    Stop,
+}
+
+/// Everything a debugger command needs: the halted core, symbol information (if an ELF was
+/// given) and a disassembler.
+pub struct CliData<'p> {
+    pub core: Core<'p>,
+    pub debug_info: Option<DebugInfo>,
+    pub capstone: Capstone,
+}
+
+/// A single interactive debugger command.
+struct Command {
+    names: &'static [&'static str],
+    help: &'static str,
+    handler: fn(&[&str], &mut CliData) -> Result<CliState>,
+}
+
+/// Dispatches lines typed at the `debug` prompt to the matching command.
+pub struct DebugCli {
+    commands: Vec<Command>,
+}
+
+impl DebugCli {
+    pub fn new() -> Self {
+        Self {
+            commands: vec![
+                Command {
+                    names: &["quit", "exit"],
+                    help: "Exit the debugger",
+                    handler: |_args, _data| Ok(CliState::Stop),
+                },
+                Command {
+                    names: &["backtrace", "bt"],
+                    help: "Print a symbolicated backtrace for the halted core",
+                    handler: |_args, data| {
+                        print_backtrace(&mut data.core, data.debug_info.as_ref())?;
+                        Ok(CliState::Continue)
+                    },
+                },
+                Command {
+                    names: &["help"],
+                    help: "List available commands",
+                    handler: |_args, _data| {
+                        println!("Available commands: quit/exit, backtrace/bt, help");
+                        Ok(CliState::Continue)
+                    },
+                },
+            ],
+        }
+    }
+
+    pub fn handle_line(&self, line: &str, data: &mut CliData) -> Result<CliState> {
+        let mut parts = line.split_whitespace();
+        let name = match parts.next() {
+            Some(name) => name,
+            None => return Ok(CliState::Continue),
+        };
+        let args: Vec<&str> = parts.collect();
+
+        match self.commands.iter().find(|c| c.names.contains(&name)) {
+            Some(command) => (command.handler)(&args, data),
+            None => {
+                println!("Unknown command '{}', type 'help' for a list.", name);
+                Ok(CliState::Continue)
+            }
+        }
+    }
+}
+
+/// Walk the call stack of a halted core, newest frame first, resolving each return address to
+/// `function (file:line)` using the DWARF debug info.
+///
+/// Unwinding stops cleanly once `main` is reached, once the frame pointer stops advancing, or
+/// once no further debug info is available for the current program counter.
+pub fn print_backtrace(core: &mut Core, debug_info: Option<&DebugInfo>) -> Result<()> {
+    let debug_info = match debug_info {
+        Some(debug_info) => debug_info,
+        None => {
+            println!("No debug info loaded (pass `--exe <path>`), can't unwind the stack.");
+            return Ok(());
+        }
+    };
+
+    let pc: u32 = core.read_core_reg(core.registers().program_counter())?;
+
+    let frames = debug_info.unwind(core, pc as u64);
+
+    if frames.is_empty() {
+        println!("<no frames found>");
+        return Ok(());
+    }
+
+    for (index, frame) in frames.iter().enumerate() {
+        let location = frame
+            .source_location
+            .as_ref()
+            .map(|location| match location.line {
+                Some(line) => format!("{}:{}", location.file, line),
+                None => location.file.clone(),
+            })
+            .unwrap_or_else(|| "<unknown location>".to_string());
+
+        println!("#{} {} ({})", index, frame.function_name, location);
+
+        if frame.function_name == "main" {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,9 @@
 mod common;
 mod debugger;
 mod info;
+mod rtt;
 mod run;
+mod shutdown;
 
 use debugger::CliState;
 
@@ -42,6 +44,9 @@ enum Cli {
     Info {
         #[structopt(flatten)]
         common: ProbeOptions,
+
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
     },
     /// Resets the target attached to the selected debug probe
     #[structopt(name = "reset")]
@@ -52,6 +57,9 @@ enum Cli {
         #[structopt(flatten)]
         common: ProbeOptions,
 
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
         /// Whether the reset pin should be asserted or deasserted. If left open, just pulse it
         assert: Option<bool>,
     },
@@ -63,10 +71,29 @@ enum Cli {
         #[structopt(flatten)]
         common: ProbeOptions,
 
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
         #[structopt(long, parse(from_os_str))]
         /// Binary to debug
         exe: Option<PathBuf>,
     },
+    /// Print a symbolicated backtrace for the halted core
+    #[structopt(name = "backtrace")]
+    Backtrace {
+        #[structopt(flatten)]
+        shared: CoreOptions,
+
+        #[structopt(flatten)]
+        common: ProbeOptions,
+
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
+        #[structopt(long, parse(from_os_str))]
+        /// ELF file to resolve function names and source locations from
+        exe: PathBuf,
+    },
     /// Dump memory from attached target
     #[structopt(name = "dump")]
     Dump {
@@ -76,6 +103,9 @@ enum Cli {
         #[structopt(flatten)]
         common: ProbeOptions,
 
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
         /// The address of the memory to dump from the target.
         #[structopt(parse(try_from_str = parse_u32))]
         loc: u32,
@@ -83,12 +113,47 @@ enum Cli {
         #[structopt(parse(try_from_str = parse_u32))]
         words: u32,
     },
+    /// Write one or more values to memory on the attached target
+    #[structopt(name = "write")]
+    Write {
+        #[structopt(flatten)]
+        shared: CoreOptions,
+
+        #[structopt(flatten)]
+        common: ProbeOptions,
+
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
+        /// Write 8-bit bytes instead of 32-bit words
+        #[structopt(long, conflicts_with_all = &["half", "word"])]
+        bytes: bool,
+
+        /// Write 16-bit half-words instead of 32-bit words
+        #[structopt(long, conflicts_with_all = &["bytes", "word"])]
+        half: bool,
+
+        /// Write 32-bit words (default)
+        #[structopt(long, conflicts_with_all = &["bytes", "half"])]
+        word: bool,
+
+        /// The address of the memory to write to.
+        #[structopt(parse(try_from_str = parse_u32))]
+        loc: u32,
+
+        /// The value(s) to write, starting at `loc` and advancing by the selected width.
+        #[structopt(parse(try_from_str = parse_u32))]
+        values: Vec<u32>,
+    },
     /// Download memory to attached target
     #[structopt(name = "download")]
     Download {
         #[structopt(flatten)]
         common: ProbeOptions,
 
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
         /// Format of the file to be downloaded to the flash. Possible values are case-insensitive.
         #[structopt(
             possible_values = &DownloadFileType::variants(),
@@ -113,13 +178,27 @@ enum Cli {
     Erase {
         #[structopt(flatten)]
         common: ProbeOptions,
+
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
     },
     /// Flash and run an ELF program
     #[structopt(name = "run")]
     Run {
+        #[structopt(flatten)]
+        shared: CoreOptions,
+
         #[structopt(flatten)]
         common: ProbeOptions,
 
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
+        /// Paint the unused stack with a canary pattern before running and report how much of
+        /// it was overwritten once the core halts.
+        #[structopt(long)]
+        check_stack_overflow: bool,
+
         /// The path to the ELF file to flash and run
         path: String,
     },
@@ -131,9 +210,44 @@ enum Cli {
         #[structopt(flatten)]
         common: ProbeOptions,
 
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
         /// The address of the memory to dump from the target.
         #[structopt(parse(try_from_str = parse_u32))]
         loc: u32,
+
+        /// Reset the core (instead of just halting it) once Ctrl-C is pressed.
+        #[structopt(long)]
+        reset_on_exit: bool,
+    },
+    /// Attach to the target and stream the RTT up-channels to stdout
+    #[structopt(name = "rtt")]
+    Rtt {
+        #[structopt(flatten)]
+        shared: CoreOptions,
+
+        #[structopt(flatten)]
+        common: ProbeOptions,
+
+        #[structopt(flatten)]
+        chip_description: ChipDescriptionOptions,
+
+        /// The address of the RTT control block in RAM. If not given, RAM is scanned for it.
+        #[structopt(long, parse(try_from_str = parse_u32))]
+        control_block_address: Option<u32>,
+
+        /// Decode the RTT stream as defmt log frames instead of printing raw bytes.
+        #[structopt(long)]
+        defmt: bool,
+
+        /// The ELF file to read the defmt symbol table from. Required when `--defmt` is set.
+        #[structopt(long, parse(from_os_str))]
+        elf: Option<PathBuf>,
+
+        /// Reset the core (instead of just halting it) once Ctrl-C is pressed.
+        #[structopt(long)]
+        reset_on_exit: bool,
     },
 }
 
@@ -144,6 +258,30 @@ struct CoreOptions {
     core: usize,
 }
 
+/// Shared option for registering a chip description at runtime, shared between commands that
+/// attach to a target.
+#[derive(StructOpt)]
+struct ChipDescriptionOptions {
+    /// Path to a YAML chip description to register before attaching. Lets a not-yet-upstreamed
+    /// or locally-modified target be used without rebuilding probe-rs.
+    #[structopt(long, parse(from_os_str))]
+    chip_description_path: Option<PathBuf>,
+}
+
+impl ChipDescriptionOptions {
+    /// Register the chip description (if one was given) with `probe_rs::config` so it's picked
+    /// up by the subsequent `simple_attach()` call.
+    fn register(&self) -> Result<()> {
+        if let Some(path) = &self.chip_description_path {
+            probe_rs::config::registry::add_target_from_yaml(path).with_context(|| {
+                format!("Failed to register chip description '{}'", path.display())
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
 fn main() -> Result<()> {
     // Initialize the logging backend.
     pretty_env_logger::init();
@@ -152,37 +290,120 @@ fn main() -> Result<()> {
 
     match matches {
         Cli::List {} => list_connected_devices(),
-        Cli::Info { common } => crate::info::show_info_of_device(&common),
+        Cli::Info {
+            common,
+            chip_description,
+        } => {
+            chip_description.register()?;
+            crate::info::show_info_of_device(&common)
+        }
         Cli::Reset {
             shared,
             common,
+            chip_description,
             assert,
-        } => reset_target_of_device(&shared, &common, assert),
+        } => {
+            chip_description.register()?;
+            reset_target_of_device(&shared, &common, assert)
+        }
         Cli::Debug {
             shared,
             common,
+            chip_description,
+            exe,
+        } => {
+            chip_description.register()?;
+            debug(&shared, &common, exe)
+        }
+        Cli::Backtrace {
+            shared,
+            common,
+            chip_description,
             exe,
-        } => debug(&shared, &common, exe),
+        } => {
+            chip_description.register()?;
+            backtrace(&shared, &common, &exe)
+        }
         Cli::Dump {
             shared,
             common,
+            chip_description,
             loc,
             words,
-        } => dump_memory(&shared, &common, loc, words),
+        } => {
+            chip_description.register()?;
+            dump_memory(&shared, &common, loc, words)
+        }
+        Cli::Write {
+            shared,
+            common,
+            chip_description,
+            bytes,
+            half,
+            word: _,
+            loc,
+            values,
+        } => {
+            chip_description.register()?;
+            write_memory(&shared, &common, bytes, half, loc, &values)
+        }
         Cli::Download {
             common,
+            chip_description,
             format,
             base_address,
             skip_bytes,
             path,
-        } => download_program_fast(common, format.into(base_address, skip_bytes), &path),
-        Cli::Run { common, path } => run::run(common, &path),
-        Cli::Erase { common } => erase(&common),
+        } => {
+            chip_description.register()?;
+            download_program_fast(common, format.into(base_address, skip_bytes), &path)
+        }
+        Cli::Run {
+            shared,
+            common,
+            chip_description,
+            check_stack_overflow,
+            path,
+        } => {
+            chip_description.register()?;
+            run::run(&shared, common, &path, check_stack_overflow)
+        }
+        Cli::Erase {
+            common,
+            chip_description,
+        } => {
+            chip_description.register()?;
+            erase(&common)
+        }
         Cli::Trace {
             shared,
             common,
+            chip_description,
             loc,
-        } => trace_u32_on_target(&shared, &common, loc),
+            reset_on_exit,
+        } => {
+            chip_description.register()?;
+            trace_u32_on_target(&shared, &common, loc, reset_on_exit)
+        }
+        Cli::Rtt {
+            shared,
+            common,
+            chip_description,
+            control_block_address,
+            defmt,
+            elf,
+            reset_on_exit,
+        } => {
+            chip_description.register()?;
+            rtt::run(
+                &shared,
+                common,
+                control_block_address,
+                defmt,
+                elf,
+                reset_on_exit,
+            )
+        }
     }
 }
 
@@ -237,6 +458,34 @@ fn dump_memory(
     Ok(())
 }
 
+fn write_memory(
+    shared_options: &CoreOptions,
+    common: &ProbeOptions,
+    bytes: bool,
+    half: bool,
+    loc: u32,
+    values: &[u32],
+) -> Result<()> {
+    let mut session = common.simple_attach()?;
+    let mut core = session.core(shared_options.core)?;
+
+    if bytes {
+        for (offset, value) in values.iter().enumerate() {
+            core.write_word_8(loc + offset as u32, *value as u8)?;
+        }
+    } else if half {
+        for (offset, value) in values.iter().enumerate() {
+            core.write_word_16(loc + (offset as u32) * 2, *value as u16)?;
+        }
+    } else {
+        for (offset, value) in values.iter().enumerate() {
+            core.write_word_32(loc + (offset as u32) * 4, *value)?;
+        }
+    }
+
+    Ok(())
+}
+
 fn download_program_fast(common: ProbeOptions, format: Format, path: &str) -> Result<()> {
     let mut session = common.simple_attach()?;
 
@@ -300,6 +549,7 @@ fn trace_u32_on_target(
     shared_options: &CoreOptions,
     common: &ProbeOptions,
     loc: u32,
+    reset_on_exit: bool,
 ) -> Result<()> {
     use scroll::{Pwrite, LE};
     use std::io::prelude::*;
@@ -311,11 +561,13 @@ fn trace_u32_on_target(
 
     let start = Instant::now();
 
+    let shutdown = shutdown::Shutdown::install()?;
+
     let mut session = common.simple_attach()?;
 
     let mut core = session.core(shared_options.core)?;
 
-    loop {
+    while !shutdown.requested() {
         // Prepare read.
         let elapsed = start.elapsed();
         let instant = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
@@ -342,6 +594,22 @@ fn trace_u32_on_target(
         let time_to_wait = poll_every_ms - instant % poll_every_ms;
         sleep(Duration::from_millis(time_to_wait));
     }
+
+    shutdown::leave_core_in_known_state(&mut core, reset_on_exit)
+}
+
+fn backtrace(shared_options: &CoreOptions, common: &ProbeOptions, exe: &Path) -> Result<()> {
+    let debug_info =
+        DebugInfo::from_file(exe).with_context(|| format!("Failed to load '{:?}'", exe))?;
+
+    let mut session = common.simple_attach()?;
+    let mut core = session.core(shared_options.core)?;
+
+    if !core.core_halted()? {
+        core.halt(std::time::Duration::from_millis(500))?;
+    }
+
+    debugger::print_backtrace(&mut core, Some(&debug_info))
 }
 
 fn debug(shared_options: &CoreOptions, common: &ProbeOptions, exe: Option<PathBuf>) -> Result<()> {
@@ -0,0 +1,143 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+use probe_rs_cli_util::common_options::ProbeOptions;
+use probe_rs_rtt::{Rtt, ScanRegion};
+
+use anyhow::{Context, Result};
+
+use crate::shutdown;
+use crate::CoreOptions;
+
+/// Attach to the target, locate the RTT control block and continuously stream all up-channels
+/// to stdout, giving firmware `println!`-style logging without an external tool.
+///
+/// When `defmt` is set, the bytes received on the up-channels are decoded as defmt log frames
+/// using the symbol table from `elf` instead of being written through verbatim.
+pub fn run(
+    shared_options: &CoreOptions,
+    common: ProbeOptions,
+    control_block_address: Option<u32>,
+    defmt: bool,
+    elf: Option<PathBuf>,
+    reset_on_exit: bool,
+) -> Result<()> {
+    let shutdown = shutdown::Shutdown::install()?;
+
+    let mut decoder = if defmt {
+        let path = elf
+            .as_ref()
+            .context("`--defmt` requires `--elf <path>` to locate the defmt symbol table")?;
+        Some(DefmtDecoder::from_elf(path)?)
+    } else {
+        None
+    };
+
+    let mut session = common.simple_attach()?;
+
+    let memory_map = session.target().memory_map.clone();
+    let scan_region = match control_block_address {
+        Some(address) => ScanRegion::Exact(address),
+        None => ScanRegion::Ram,
+    };
+
+    let mut rtt = {
+        let mut core = session.core(shared_options.core)?;
+        Rtt::attach_region(&mut core, &memory_map, &scan_region)?
+    };
+
+    let mut buf = [0_u8; 1024];
+    let stdout = std::io::stdout();
+
+    while !shutdown.requested() {
+        let mut core = session.core(shared_options.core)?;
+
+        for (_number, channel) in rtt.up_channels().iter() {
+            let count = channel.read(&mut core, &mut buf)?;
+            if count == 0 {
+                continue;
+            }
+
+            match &mut decoder {
+                Some(decoder) => decoder.decode_and_print(&buf[..count]),
+                None => {
+                    let mut handle = stdout.lock();
+                    handle.write_all(&buf[..count])?;
+                    handle.flush()?;
+                }
+            }
+        }
+
+        drop(core);
+        sleep(Duration::from_millis(50));
+    }
+
+    let mut core = session.core(shared_options.core)?;
+    shutdown::leave_core_in_known_state(&mut core, reset_on_exit)
+}
+
+/// Incrementally decodes a stream of defmt-encoded bytes into formatted log lines.
+///
+/// Bytes are fed in as they arrive over RTT; frames that are split across two reads are
+/// buffered internally and completed once the rest of the frame arrives.
+struct DefmtDecoder {
+    locations: defmt_decoder::Locations,
+    // Borrows from the `&'static Table` leaked in `from_elf`. That table is never reclaimed
+    // (it lives for the process lifetime), so the borrow stays valid for as long as this does.
+    stream_decoder: Box<dyn defmt_decoder::StreamDecoder + 'static>,
+}
+
+impl DefmtDecoder {
+    fn from_elf(path: &std::path::Path) -> Result<Self> {
+        let elf = std::fs::read(path)
+            .with_context(|| format!("Failed to read ELF file '{}'", path.display()))?;
+
+        let table = defmt_decoder::Table::parse(&elf)?
+            .context("No defmt symbol table found in the given ELF file")?;
+        let locations = table.get_locations(&elf)?;
+
+        // `new_stream_decoder` borrows the table for the decoder's lifetime, and we need the
+        // decoder to outlive this function (it keeps decoding RTT output for as long as `rtt`
+        // runs). Leak the table so that borrow can be `'static` instead of trying to store a
+        // `Table` and a decoder that borrows it in the same struct.
+        let table: &'static defmt_decoder::Table = Box::leak(Box::new(table));
+        let stream_decoder = table.new_stream_decoder();
+
+        Ok(Self {
+            locations,
+            stream_decoder,
+        })
+    }
+
+    /// Feed newly received bytes into the decoder and print every complete frame found so far.
+    fn decode_and_print(&mut self, bytes: &[u8]) {
+        self.stream_decoder.received(bytes);
+
+        loop {
+            match self.stream_decoder.decode() {
+                Ok(frame) => {
+                    let level = frame
+                        .level()
+                        .map(|level| format!("{} ", level))
+                        .unwrap_or_default();
+
+                    let location = self
+                        .locations
+                        .get(&frame.index())
+                        .map(|loc| format!(" [{}:{}]", loc.file.display(), loc.line))
+                        .unwrap_or_default();
+
+                    println!("{}{}{}", level, frame.display(false), location);
+                }
+                // Not enough bytes yet for a full frame; wait for the next read.
+                Err(defmt_decoder::DecodeError::UnexpectedEof) => break,
+                Err(defmt_decoder::DecodeError::Malformed) => {
+                    log::error!("Failed to decode defmt frame, remaining data may be out of sync");
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use goblin::elf::Elf;
+
+use probe_rs::{
+    flashing::{download_file, Format},
+    MemoryInterface,
+};
+use probe_rs_cli_util::common_options::ProbeOptions;
+
+use anyhow::{Context, Result};
+
+use crate::shutdown;
+use crate::CoreOptions;
+
+/// The largest stack window we're willing to paint and read back. Bounds the extra probe
+/// traffic the overflow check adds; stack usage deeper than this overwrites the whole window
+/// and is reported as a likely overflow.
+const MAX_CANARY_BYTES: u32 = 8 * 1024;
+
+/// The byte pattern used to "paint" the unused stack before running.
+const CANARY_BYTE: u8 = 0xAA;
+
+/// Flash `path` to the attached target and run it.
+///
+/// When `check_stack_overflow` is set, the unused stack is painted with a canary pattern
+/// before the core starts, and inspected once it halts to report how much stack headroom
+/// was used.
+pub fn run(
+    shared_options: &CoreOptions,
+    common: ProbeOptions,
+    path: &str,
+    check_stack_overflow: bool,
+) -> Result<()> {
+    let shutdown = shutdown::Shutdown::install()?;
+
+    let mut session = common.simple_attach()?;
+
+    download_file(&mut session, Path::new(path), Format::Elf)
+        .with_context(|| format!("Failed to flash '{}'", path))?;
+
+    let canary = if check_stack_overflow {
+        paint_stack_canary(&mut session, shared_options, path)?
+    } else {
+        None
+    };
+
+    {
+        let mut core = session.core(shared_options.core)?;
+        core.reset_and_halt(std::time::Duration::from_millis(500))?;
+        core.run()?;
+    }
+
+    // Wait for the core to halt, either by hitting a breakpoint, panicking, or the user
+    // requesting a shutdown (Ctrl-C), which we also treat as a halt so the probe is left in a
+    // known state instead of exiting with the target running unattended.
+    loop {
+        let mut core = session.core(shared_options.core)?;
+        if core.core_halted()? || shutdown.requested() {
+            break;
+        }
+        drop(core);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    {
+        let mut core = session.core(shared_options.core)?;
+        shutdown::leave_core_in_known_state(&mut core, false)?;
+    }
+
+    if let Some(canary) = canary {
+        report_stack_usage(&mut session, shared_options, canary)?;
+    }
+
+    Ok(())
+}
+
+/// The painted stack window: its lowest address and how many bytes were painted.
+struct Canary {
+    low_address: u32,
+    size: u32,
+}
+
+/// Locate the RAM region from the target's memory map and the initial stack pointer from the
+/// ELF, then paint the unused
+/// stack with [`CANARY_BYTE`], working down from the initial SP and bounded by
+/// [`MAX_CANARY_BYTES`] so the window stays anchored where the stack is actually used first.
+fn paint_stack_canary(
+    session: &mut probe_rs::Session,
+    shared_options: &CoreOptions,
+    path: &str,
+) -> Result<Option<Canary>> {
+    let ram = session
+        .target()
+        .memory_map
+        .iter()
+        .find_map(|region| region.as_ram_region())
+        .cloned();
+
+    let ram = match ram {
+        Some(ram) => ram,
+        None => {
+            log::warn!("Could not determine a RAM region; skipping stack overflow check");
+            return Ok(None);
+        }
+    };
+
+    let mut file = File::open(path).with_context(|| format!("Failed to open '{}'", path))?;
+    let mut core = session.core(shared_options.core)?;
+
+    let stack_pointer = match initial_stack_pointer(&mut file) {
+        Some(sp) => sp,
+        None => {
+            log::warn!("Could not determine the initial stack pointer; skipping stack overflow check");
+            return Ok(None);
+        }
+    };
+
+    let ram_start = ram.range.start as u32;
+    if stack_pointer <= ram_start {
+        log::warn!("Initial stack pointer is not above the start of RAM; skipping stack overflow check");
+        return Ok(None);
+    }
+
+    // Anchor the (possibly capped) window at the SP end of the stack rather than the bottom of
+    // RAM: a real overflow eats into the stack from the top down, so that's where a bounded
+    // window needs to sit to actually catch anything short of a total wipeout.
+    let size = (stack_pointer - ram_start).min(MAX_CANARY_BYTES);
+    let low_address = stack_pointer.saturating_sub(size).max(ram_start);
+    let pattern = vec![CANARY_BYTE; size as usize];
+    core.write_8(low_address, &pattern)?;
+
+    Ok(Some(Canary { low_address, size }))
+}
+
+/// Read the vector table's initial stack pointer entry (the first word of the `.vector_table`
+/// section, or of the lowest-addressed loadable section if there's no section by that name)
+/// straight out of the ELF, which is where Cortex-M cores load SP from on reset.
+fn initial_stack_pointer(file: &mut File) -> Option<u32> {
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer).ok()?;
+
+    let elf = Elf::parse(&buffer).ok()?;
+
+    let vector_table = elf
+        .section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".vector_table"))
+        .or_else(|| {
+            elf.section_headers
+                .iter()
+                .filter(|header| header.sh_addr != 0)
+                .min_by_key(|header| header.sh_addr)
+        })?;
+
+    let offset = vector_table.sh_offset as usize;
+    let bytes: [u8; 4] = buffer.get(offset..offset + 4)?.try_into().ok()?;
+
+    Some(u32::from_le_bytes(bytes))
+}
+
+/// Read the painted stack region back and report how much of it was overwritten.
+fn report_stack_usage(
+    session: &mut probe_rs::Session,
+    shared_options: &CoreOptions,
+    canary: Canary,
+) -> Result<()> {
+    let mut core = session.core(shared_options.core)?;
+
+    let mut data = vec![0_u8; canary.size as usize];
+    core.read_8(canary.low_address, &mut data)?;
+
+    let used = data.iter().position(|&b| b != CANARY_BYTE);
+
+    match used {
+        Some(0) => {
+            println!(
+                "Stack overflow check: the entire painted window ({} bytes from 0x{:08x}) was \
+                 overwritten, this likely indicates a stack overflow.",
+                canary.size, canary.low_address
+            );
+        }
+        headroom => {
+            // No overwritten byte at all means the whole window is still headroom.
+            let headroom = headroom.unwrap_or(canary.size as usize);
+            println!(
+                "Stack overflow check: {} bytes of headroom remained before the painted region \
+                 (0x{:08x}, {} bytes) was touched.",
+                headroom, canary.low_address, canary.size
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+
+/// Shared flag set once SIGINT/SIGTERM is received.
+///
+/// Streaming commands (`trace`, `rtt`, ...) poll [`Shutdown::requested`] at the top of each
+/// loop iteration instead of running an unconditional `loop {}` that can only be killed with a
+/// hard interrupt, leaving the target in an arbitrary state.
+#[derive(Clone)]
+pub struct Shutdown {
+    flag: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Install the signal handler and return a handle that loops can poll.
+    pub fn install() -> Result<Self> {
+        let flag = Arc::new(AtomicBool::new(false));
+
+        let handler_flag = flag.clone();
+        ctrlc::set_handler(move || {
+            handler_flag.store(true, Ordering::SeqCst);
+        })?;
+
+        Ok(Self { flag })
+    }
+
+    /// Whether a shutdown has been requested since this handle was installed.
+    pub fn requested(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+/// Halt the core so the probe is left in a known state, optionally resetting it first.
+pub fn leave_core_in_known_state(core: &mut probe_rs::Core, reset: bool) -> Result<()> {
+    if reset {
+        core.reset_and_halt(std::time::Duration::from_millis(500))?;
+    } else {
+        core.halt(std::time::Duration::from_millis(500))?;
+    }
+
+    Ok(())
+}